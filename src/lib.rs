@@ -1,5 +1,9 @@
 mod loader;
+mod material;
+mod mesh;
+mod settings;
 pub use loader::*;
+pub use settings::*;
 
 use bevy::app::prelude::*;
 use bevy::asset::AddAsset;
@@ -1,14 +1,44 @@
 use anyhow::Result;
 use bevy::asset::{AssetLoader, LoadContext, LoadedAsset};
-use bevy::render::{
-    mesh::{Indices, Mesh, VertexAttributeValues},
-    pipeline::PrimitiveTopology,
-};
+use bevy::ecs::world::{FromWorld, World};
+use bevy::render::renderer::RenderDevice;
+use bevy::render::texture::CompressedImageFormats;
 use bevy::utils::BoxedFuture;
 use thiserror::Error;
 
-#[derive(Default)]
-pub struct ObjLoader;
+use crate::mesh::{build_flat_mesh, build_mesh, detect_pnt};
+use crate::ObjLoaderSettings;
+
+pub struct ObjLoader {
+    settings: ObjLoaderSettings,
+    supported_compressed_formats: CompressedImageFormats,
+}
+
+impl ObjLoader {
+    /// Creates a loader that applies the given `settings` to every OBJ file
+    /// it loads. Textures requiring GPU-side decompression (DDS, KTX2) won't
+    /// be supported unless the loader is instead constructed through
+    /// `FromWorld`, which detects the formats the `RenderDevice` supports.
+    pub fn new(settings: ObjLoaderSettings) -> Self {
+        Self {
+            settings,
+            supported_compressed_formats: CompressedImageFormats::NONE,
+        }
+    }
+}
+
+impl FromWorld for ObjLoader {
+    fn from_world(world: &mut World) -> Self {
+        let supported_compressed_formats = match world.get_resource::<RenderDevice>() {
+            Some(render_device) => CompressedImageFormats::from_features(render_device.features()),
+            None => CompressedImageFormats::NONE,
+        };
+        Self {
+            settings: ObjLoaderSettings::default(),
+            supported_compressed_formats,
+        }
+    }
+}
 
 impl AssetLoader for ObjLoader {
     fn load<'a>(
@@ -16,7 +46,15 @@ impl AssetLoader for ObjLoader {
         bytes: &'a [u8],
         load_context: &'a mut bevy::asset::LoadContext,
     ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
-        Box::pin(async move { Ok(load_obj(bytes, load_context).await?) })
+        Box::pin(async move {
+            Ok(load_obj(
+                bytes,
+                &self.settings,
+                self.supported_compressed_formats,
+                load_context,
+            )
+            .await?)
+        })
     }
 
     fn extensions(&self) -> &[&str] {
@@ -31,87 +69,101 @@ pub enum ObjError {
     Gltf(#[from] obj::ObjError),
     #[error("Unknown vertex format.")]
     UnknownVertexFormat,
+    #[error("Failed to read a file referenced by the OBJ asset.")]
+    Io(#[from] bevy::asset::AssetIoError),
+    #[error("Failed to load a texture referenced by the OBJ's material: {0}")]
+    Texture(String),
+}
+
+/// A file with no `o`/`g` statements still parses into a single "default"
+/// group; that one shouldn't be labeled since it's identical to the merged
+/// mesh `load_obj` sets as the default asset. A file with exactly one
+/// explicit `g`/`o` named "default" is indistinguishable from this at the
+/// `RawObj` level, so it also goes unlabeled — the tradeoff favors the far
+/// more common implicit case.
+fn is_implicit_default_group(named_groups: &[(String, Vec<std::ops::Range<usize>>)]) -> bool {
+    named_groups.len() == 1 && named_groups[0].0 == "default"
 }
 
 async fn load_obj<'a, 'b>(
     bytes: &'a [u8],
+    settings: &ObjLoaderSettings,
+    supported_compressed_formats: CompressedImageFormats,
     load_context: &'a mut LoadContext<'b>,
 ) -> Result<(), ObjError> {
-    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
-    load_obj_from_bytes(bytes, &mut mesh)?;
-    load_context.set_default_asset(LoadedAsset::new(mesh));
-    Ok(())
-}
-
-fn load_obj_from_bytes(bytes: &[u8], mesh: &mut Mesh) -> Result<(), ObjError> {
     let raw = obj::raw::parse_obj(bytes)?;
 
-    // Get the most complete vertex representation
-    //  3 => Position, Normal, Texture
-    //  2 => Position, Normal
-    //  1 => Position
-    let mut pnt = 3;
-    for polygon in &raw.polygons {
-        use obj::raw::object::Polygon;
-        match polygon {
-            Polygon::P(_) => pnt = std::cmp::min(pnt, 1),
-            Polygon::PN(_) => pnt = std::cmp::min(pnt, 2),
-            _ => {}
+    // `usemtl` groups, keyed by material name, are what we split the mesh
+    // into when the file has materials to render each group with.
+    let material_groups: Vec<(String, Vec<std::ops::Range<usize>>)> = raw
+        .meshes
+        .iter()
+        .map(|(name, group)| (name.clone(), group.polygons.clone()))
+        .collect();
+    // `o`/`g` groups, keyed by their own name, are exposed as labeled
+    // sub-assets so a single OBJ can be addressed submesh-by-submesh.
+    let named_groups: Vec<(String, Vec<std::ops::Range<usize>>)> = raw
+        .groups
+        .iter()
+        .map(|(name, group)| (name.clone(), group.polygons.clone()))
+        .collect();
+    let material_libraries = raw.material_libraries.clone();
+
+    let pnt = detect_pnt(&raw.polygons);
+    let flat = build_flat_mesh(raw, pnt, settings)?;
+
+    if !is_implicit_default_group(&named_groups) {
+        for (name, polygon_ranges) in &named_groups {
+            let indices = crate::mesh::group_indices(&flat, polygon_ranges);
+            if indices.is_empty() {
+                continue;
+            }
+            let mesh = build_mesh(&flat, indices);
+            load_context.set_labeled_asset(name, LoadedAsset::new(mesh));
         }
     }
 
-    match pnt {
-        1 => {
-            let obj: obj::Obj<obj::Position, u32> = obj::Obj::new(raw)?;
-            set_position_data(mesh, obj.vertices.iter().map(|v| v.position).collect());
-            set_normal_data(mesh, obj.vertices.iter().map(|_| [0., 0., 0.]).collect());
-            set_uv_data(mesh, obj.vertices.iter().map(|_| [0., 0., 0.]).collect());
-            set_mesh_indices(mesh, obj);
-        }
-        2 => {
-            let obj: obj::Obj<obj::Vertex, u32> = obj::Obj::new(raw)?;
-            set_position_data(mesh, obj.vertices.iter().map(|v| v.position).collect());
-            set_normal_data(mesh, obj.vertices.iter().map(|v| v.normal).collect());
-            set_uv_data(mesh, obj.vertices.iter().map(|_| [0., 0., 0.]).collect());
-            set_mesh_indices(mesh, obj);
-        }
-        3 => {
-            let obj: obj::Obj<obj::TexturedVertex, u32> = obj::Obj::new(raw)?;
-            set_position_data(mesh, obj.vertices.iter().map(|v| v.position).collect());
-            set_normal_data(mesh, obj.vertices.iter().map(|v| v.normal).collect());
-            set_uv_data(
-                mesh,
-                obj.vertices
-                    .iter()
-                    // Flip UV for correct values
-                    .map(|v| [v.texture[0], 1.0 - v.texture[1], v.texture[2]])
-                    .collect(),
-            );
-            set_mesh_indices(mesh, obj);
-        }
-        _ => return Err(ObjError::UnknownVertexFormat),
+    // The merged mesh remains the default asset so existing handles into an
+    // OBJ file keep working regardless of how many groups it contains.
+    let merged_mesh = build_mesh(&flat, flat.indices.clone());
+    load_context.set_default_asset(LoadedAsset::new(merged_mesh));
+
+    if !material_libraries.is_empty() && !material_groups.is_empty() {
+        let scene = crate::material::load_scene(
+            &material_libraries,
+            &material_groups,
+            &flat,
+            supported_compressed_formats,
+            load_context,
+        )
+        .await?;
+        load_context.set_labeled_asset("Scene", LoadedAsset::new(scene));
     }
 
     Ok(())
 }
 
-fn set_position_data(mesh: &mut Mesh, data: Vec<[f32; 3]>) {
-    let positions = VertexAttributeValues::Float32x3(data);
-    mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-fn set_normal_data(mesh: &mut Mesh, data: Vec<[f32; 3]>) {
-    let normals = VertexAttributeValues::Float32x3(data);
-    mesh.set_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
-}
+    #[test]
+    fn is_implicit_default_group_is_true_only_for_the_lone_unnamed_group() {
+        assert!(is_implicit_default_group(&[("default".to_string(), vec![])]));
+    }
 
-fn set_uv_data(mesh: &mut Mesh, data: Vec<[f32; 3]>) {
-    let uvs = VertexAttributeValues::Float32x3(data);
-    mesh.set_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
-}
+    #[test]
+    fn is_implicit_default_group_is_false_for_a_single_explicit_group() {
+        // A file with exactly one explicit `g foo` still gets its own label,
+        // even though it's also the only group in `named_groups`.
+        assert!(!is_implicit_default_group(&[("foo".to_string(), vec![])]));
+    }
 
-fn set_mesh_indices<T>(mesh: &mut Mesh, obj: obj::Obj<T, u32>) {
-    mesh.set_indices(Some(Indices::U32(
-        obj.indices.iter().map(|i| *i as u32).collect(),
-    )));
+    #[test]
+    fn is_implicit_default_group_is_false_when_multiple_groups_are_present() {
+        assert!(!is_implicit_default_group(&[
+            ("default".to_string(), vec![]),
+            ("other".to_string(), vec![]),
+        ]));
+    }
 }
@@ -0,0 +1,203 @@
+//! Loads companion `.mtl` material libraries and assembles the textured
+//! groups of an OBJ file into a [`Scene`] of `PbrBundle`s, one per material.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use bevy::asset::{AssetPath, Handle, LoadContext, LoadedAsset};
+use bevy::ecs::world::World;
+use bevy::pbr::{AlphaMode, PbrBundle, StandardMaterial};
+use bevy::render::color::Color;
+use bevy::render::mesh::Mesh;
+use bevy::render::texture::{
+    CompressedImageFormats, Extent3d, Texture, TextureDimension, TextureFormat,
+};
+use bevy::scene::Scene;
+use bevy::transform::prelude::Transform;
+use obj::raw::material::Material;
+
+use crate::mesh::{build_mesh, FlatMesh};
+use crate::ObjError;
+
+/// Parses every `.mtl` file referenced by `mtllib` into a map of material
+/// name to its raw material description.
+async fn load_material_libraries<'a>(
+    libraries: &[String],
+    load_context: &LoadContext<'a>,
+) -> Result<HashMap<String, Material>, ObjError> {
+    let mut materials = HashMap::new();
+    let base_path = load_context.path().parent().unwrap_or_else(|| Path::new(""));
+
+    for library in libraries {
+        let library_path = base_path.join(library);
+        let bytes = load_context.read_asset_bytes(&library_path).await?;
+        let mtl = obj::raw::material::parse_mtl(bytes.as_slice())?;
+        for material in mtl.materials {
+            materials.insert(material.name.clone(), material);
+        }
+    }
+
+    Ok(materials)
+}
+
+/// The compressed GPU texture container formats a `.mtl` might reference via
+/// `map_Kd`/`map_Bump`/etc., and the `CompressedImageFormats` flag required
+/// to upload each one, mirroring the check core Bevy's glTF loader runs
+/// before handing a texture to the `RenderDevice`.
+fn required_compressed_format(extension: &str) -> Option<CompressedImageFormats> {
+    match extension.to_ascii_lowercase().as_str() {
+        "dds" => Some(CompressedImageFormats::BC),
+        "ktx2" | "basis" => {
+            Some(CompressedImageFormats::ASTC_LDR | CompressedImageFormats::ETC2)
+        }
+        _ => None,
+    }
+}
+
+/// Decodes a texture referenced by a material (e.g. `map_Kd`) and registers
+/// it as a labeled sub-asset of the OBJ file being loaded.
+///
+/// Container formats that need GPU-side decompression (DDS, KTX2) are
+/// checked against `supported_compressed_formats` before we bother reading
+/// the file, the same guard core Bevy's glTF loader applies via its
+/// `RenderDevice`-derived `CompressedImageFormats`.
+async fn load_texture<'a>(
+    map: &str,
+    label: &str,
+    supported_compressed_formats: CompressedImageFormats,
+    load_context: &mut LoadContext<'a>,
+) -> Result<Handle<Texture>, ObjError> {
+    let base_path = load_context.path().parent().unwrap_or_else(|| Path::new(""));
+    let texture_path = base_path.join(map);
+
+    if let Some(extension) = texture_path.extension().and_then(|ext| ext.to_str()) {
+        if let Some(required) = required_compressed_format(extension) {
+            if !supported_compressed_formats.intersects(required) {
+                return Err(ObjError::Texture(format!(
+                    "{} uses a compressed texture format unsupported by this GPU",
+                    map
+                )));
+            }
+        }
+    }
+
+    let bytes = load_context.read_asset_bytes(&texture_path).await?;
+    let image = image::load_from_memory(&bytes)
+        .map_err(|error| ObjError::Texture(format!("failed to decode {}: {}", map, error)))?
+        .into_rgba8();
+    let (width, height) = image.dimensions();
+    let texture = Texture::new(
+        Extent3d::new(width, height, 1),
+        TextureDimension::D2,
+        image.into_raw(),
+        TextureFormat::Rgba8UnormSrgb,
+    );
+
+    load_context.set_labeled_asset(label, LoadedAsset::new(texture));
+    Ok(load_context.get_handle(AssetPath::new_ref(load_context.path(), Some(label))))
+}
+
+/// Builds a `StandardMaterial` from a raw `.mtl` material, loading any
+/// textures it references as labeled sub-assets of this OBJ file.
+async fn build_standard_material<'a>(
+    name: &str,
+    material: &Material,
+    supported_compressed_formats: CompressedImageFormats,
+    load_context: &mut LoadContext<'a>,
+) -> Result<StandardMaterial, ObjError> {
+    let mut standard = StandardMaterial::default();
+
+    if let Some(kd) = material.kd {
+        standard.base_color = Color::rgb(kd.0, kd.1, kd.2);
+    }
+    if let Some(d) = material.d {
+        standard.base_color.set_a(d);
+        // `set_a` alone has no visible effect: `StandardMaterial` only reads
+        // the alpha channel when `alpha_mode` opts out of `Opaque`.
+        if d < 1.0 {
+            standard.alpha_mode = AlphaMode::Blend;
+        }
+    }
+    if let Some(ns) = material.ns {
+        // `Ns` is a Phong specular exponent in roughly [0, 1000]; map it onto
+        // Bevy's [0, 1] perceptual roughness the same way other OBJ-to-PBR
+        // conversions approximate it.
+        standard.perceptual_roughness = (1.0 - (ns / 1000.0).clamp(0.0, 1.0)).clamp(0.089, 1.0);
+    }
+
+    if let Some(map_kd) = &material.map_kd {
+        let label = format!("Texture/{}/BaseColor", name);
+        standard.base_color_texture = Some(
+            load_texture(map_kd, &label, supported_compressed_formats, load_context).await?,
+        );
+    }
+    if let Some(map_bump) = &material.map_bump {
+        let label = format!("Texture/{}/Normal", name);
+        standard.normal_map_texture = Some(
+            load_texture(map_bump, &label, supported_compressed_formats, load_context).await?,
+        );
+    }
+    // `map_Ks` is a Phong specular map; `StandardMaterial` has no slot for
+    // it. `metallic_roughness_texture` expects roughness in G and metalness
+    // in B, which a specular map doesn't carry, so wiring it in there would
+    // just produce random-looking metal/roughness values per texel. Drop it
+    // rather than loading a texture for nothing.
+
+    Ok(standard)
+}
+
+/// Assembles a `Scene` of `PbrBundle`s, one per material group, from the
+/// flattened mesh geometry and the `.mtl` libraries named in
+/// `raw_material_libraries`.
+pub(crate) async fn load_scene<'a>(
+    raw_material_libraries: &[String],
+    mesh_groups: &[(String, Vec<std::ops::Range<usize>>)],
+    flat: &FlatMesh,
+    supported_compressed_formats: CompressedImageFormats,
+    load_context: &mut LoadContext<'a>,
+) -> Result<Scene, ObjError> {
+    let materials = load_material_libraries(raw_material_libraries, load_context).await?;
+
+    let mut world = World::default();
+
+    for (material_name, polygon_ranges) in mesh_groups {
+        let indices = crate::mesh::group_indices(flat, polygon_ranges);
+        if indices.is_empty() {
+            continue;
+        }
+
+        let mesh: Mesh = build_mesh(flat, indices);
+        let mesh_label = format!("Mesh/{}", material_name);
+        load_context.set_labeled_asset(&mesh_label, LoadedAsset::new(mesh));
+        let mesh_handle = load_context
+            .get_handle(AssetPath::new_ref(load_context.path(), Some(mesh_label.as_str())));
+
+        let standard_material = match materials.get(material_name) {
+            Some(material) => {
+                build_standard_material(
+                    material_name,
+                    material,
+                    supported_compressed_formats,
+                    load_context,
+                )
+                .await?
+            }
+            None => StandardMaterial::default(),
+        };
+        let material_label = format!("Material/{}", material_name);
+        load_context.set_labeled_asset(&material_label, LoadedAsset::new(standard_material));
+        let material_handle = load_context.get_handle(AssetPath::new_ref(
+            load_context.path(),
+            Some(material_label.as_str()),
+        ));
+
+        world.spawn().insert_bundle(PbrBundle {
+            mesh: mesh_handle,
+            material: material_handle,
+            transform: Transform::default(),
+            ..Default::default()
+        });
+    }
+
+    Ok(Scene::new(world))
+}
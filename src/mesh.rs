@@ -0,0 +1,451 @@
+//! Geometry helpers shared by the single-mesh and scene loading paths.
+
+use bevy::math::Vec3;
+use bevy::render::mesh::{Indices, Mesh, VertexAttributeValues};
+use bevy::render::pipeline::PrimitiveTopology;
+use obj::raw::object::Polygon;
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::{ObjError, ObjLoaderSettings};
+
+/// The merged geometry produced by flattening every polygon in a `RawObj`
+/// into a single indexed vertex buffer, plus the bookkeeping needed to slice
+/// that buffer back into the ranges contributed by each individual polygon.
+pub(crate) struct FlatMesh {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub uvs: Vec<[f32; 2]>,
+    pub indices: Vec<u32>,
+    /// `index_offsets[i]..index_offsets[i + 1]` is the slice of `indices`
+    /// contributed by `raw.polygons[i]`.
+    pub index_offsets: Vec<usize>,
+    /// Per-vertex tangents, generated via mikktspace when the file has both
+    /// normals and UVs. `None` when there isn't enough data to derive them.
+    pub tangents: Option<Vec<[f32; 4]>>,
+}
+
+/// Picks the most complete vertex representation available in the file.
+///
+///  3 => Position, Normal, Texture
+///  2 => Position, Normal
+///  1 => Position
+pub(crate) fn detect_pnt(polygons: &[Polygon]) -> usize {
+    let mut pnt = 3;
+    for polygon in polygons {
+        match polygon {
+            Polygon::P(_) => pnt = std::cmp::min(pnt, 1),
+            Polygon::PN(_) => pnt = std::cmp::min(pnt, 2),
+            _ => {}
+        }
+    }
+    pnt
+}
+
+fn triangle_count(polygon: &Polygon) -> usize {
+    let vertex_count = match polygon {
+        Polygon::P(v) => v.len(),
+        Polygon::PT(v) => v.len(),
+        Polygon::PN(v) => v.len(),
+        Polygon::PTN(v) => v.len(),
+    };
+    vertex_count.saturating_sub(2)
+}
+
+/// Computes the prefix sum of triangle-fan index counts per polygon, so a
+/// `Range<usize>` over `raw.polygons` can be turned into a `Range<usize>`
+/// over the flattened index buffer produced by [`build_flat_mesh`].
+fn polygon_index_offsets(polygons: &[Polygon]) -> Vec<usize> {
+    let mut offsets = Vec::with_capacity(polygons.len() + 1);
+    let mut acc = 0usize;
+    offsets.push(acc);
+    for polygon in polygons {
+        acc += triangle_count(polygon) * 3;
+        offsets.push(acc);
+    }
+    offsets
+}
+
+/// Flattens every polygon in `raw` into a single indexed vertex buffer,
+/// triangulating obj-rs's internal vertex/index representation for the
+/// given vertex completeness (`pnt`, see [`detect_pnt`]).
+pub(crate) fn build_flat_mesh(
+    raw: obj::raw::RawObj,
+    pnt: usize,
+    settings: &ObjLoaderSettings,
+) -> Result<FlatMesh, ObjError> {
+    let index_offsets = polygon_index_offsets(&raw.polygons);
+
+    let (positions, normals, uvs, indices) = match pnt {
+        1 => {
+            let obj: obj::Obj<obj::Position, u32> = obj::Obj::new(raw)?;
+            let positions: Vec<[f32; 3]> = obj.vertices.iter().map(|v| v.position).collect();
+            // The file has no normals of its own; derive area-weighted
+            // smooth vertex normals from the triangulated geometry instead
+            // of leaving lighting completely broken.
+            let normals = compute_smooth_normals(&positions, &obj.indices);
+            let uvs = vec![[0., 0.]; obj.vertices.len()];
+            (positions, normals, uvs, obj.indices)
+        }
+        2 => {
+            let obj: obj::Obj<obj::Vertex, u32> = obj::Obj::new(raw)?;
+            let positions = obj.vertices.iter().map(|v| v.position).collect();
+            let normals = obj.vertices.iter().map(|v| v.normal).collect();
+            let uvs = vec![[0., 0.]; obj.vertices.len()];
+            (positions, normals, uvs, obj.indices)
+        }
+        3 => {
+            let obj: obj::Obj<obj::TexturedVertex, u32> = obj::Obj::new(raw)?;
+            let positions = obj.vertices.iter().map(|v| v.position).collect();
+            let normals = obj.vertices.iter().map(|v| v.normal).collect();
+            let uvs = obj
+                .vertices
+                .iter()
+                .map(|v| {
+                    let v1 = if settings.flip_uv_y {
+                        1.0 - v.texture[1]
+                    } else {
+                        v.texture[1]
+                    };
+                    [v.texture[0], v1]
+                })
+                .collect();
+            (positions, normals, uvs, obj.indices)
+        }
+        _ => return Err(ObjError::UnknownVertexFormat),
+    };
+
+    let positions: Vec<[f32; 3]> = positions
+        .into_iter()
+        .map(settings.coordinate_conversion)
+        .collect();
+    let normals: Vec<[f32; 3]> = normals
+        .into_iter()
+        .map(settings.coordinate_conversion)
+        .collect();
+
+    // Tangents require both normals and UVs, which only the `pnt == 3`
+    // vertex format carries.
+    let tangents = if settings.generate_tangents && pnt == 3 {
+        compute_tangents(&positions, &normals, &uvs, &indices)
+    } else {
+        None
+    };
+
+    Ok(FlatMesh {
+        positions,
+        normals,
+        uvs,
+        indices,
+        index_offsets,
+        tangents,
+    })
+}
+
+/// Adapts [`FlatMesh`]'s flat triangle-list buffers to the `Geometry` trait
+/// mikktspace needs to walk the mesh face by face.
+struct TangentGeometry<'a> {
+    positions: &'a [[f32; 3]],
+    normals: &'a [[f32; 3]],
+    uvs: &'a [[f32; 2]],
+    indices: &'a [u32],
+    tangents: Vec<[f32; 4]>,
+}
+
+impl<'a> TangentGeometry<'a> {
+    fn vertex_index(&self, face: usize, vert: usize) -> usize {
+        self.indices[face * 3 + vert] as usize
+    }
+}
+
+impl<'a> bevy_mikktspace::Geometry for TangentGeometry<'a> {
+    fn num_faces(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    fn num_vertices_of_face(&self, _face: usize) -> usize {
+        3
+    }
+
+    fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.positions[self.vertex_index(face, vert)]
+    }
+
+    fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.normals[self.vertex_index(face, vert)]
+    }
+
+    fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+        self.uvs[self.vertex_index(face, vert)]
+    }
+
+    fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+        let index = self.vertex_index(face, vert);
+        self.tangents[index] = tangent;
+    }
+}
+
+/// Runs mikktspace over the flattened geometry to produce per-vertex
+/// tangents, which is what lets normal maps render correctly. Returns `None`
+/// if mikktspace can't generate a tangent basis for this mesh.
+fn compute_tangents(
+    positions: &[[f32; 3]],
+    normals: &[[f32; 3]],
+    uvs: &[[f32; 2]],
+    indices: &[u32],
+) -> Option<Vec<[f32; 4]>> {
+    let mut geometry = TangentGeometry {
+        positions,
+        normals,
+        uvs,
+        indices,
+        tangents: vec![[1.0, 0.0, 0.0, 1.0]; positions.len()],
+    };
+
+    if bevy_mikktspace::generate_tangents(&mut geometry) {
+        Some(geometry.tangents)
+    } else {
+        None
+    }
+}
+
+/// Derives smooth per-vertex normals for a mesh that has none of its own.
+///
+/// For each triangle this accumulates its (unnormalized, so implicitly
+/// area-weighted) face normal into every vertex it touches, then normalizes
+/// the per-vertex sum. Degenerate triangles contribute nothing, and a vertex
+/// that ends up with no contribution at all falls back to `[0, 1, 0]` rather
+/// than producing a NaN.
+fn compute_smooth_normals(positions: &[[f32; 3]], indices: &[u32]) -> Vec<[f32; 3]> {
+    let mut accumulated = vec![Vec3::ZERO; positions.len()];
+
+    for triangle in indices.chunks_exact(3) {
+        let (i0, i1, i2) = (triangle[0] as usize, triangle[1] as usize, triangle[2] as usize);
+        let p0 = Vec3::from(positions[i0]);
+        let p1 = Vec3::from(positions[i1]);
+        let p2 = Vec3::from(positions[i2]);
+
+        let face_normal = (p1 - p0).cross(p2 - p0);
+        if face_normal.length_squared() == 0.0 {
+            continue;
+        }
+
+        accumulated[i0] += face_normal;
+        accumulated[i1] += face_normal;
+        accumulated[i2] += face_normal;
+    }
+
+    accumulated
+        .into_iter()
+        .map(|normal| {
+            if normal.length_squared() == 0.0 {
+                [0.0, 1.0, 0.0]
+            } else {
+                normal.normalize().into()
+            }
+        })
+        .collect()
+}
+
+/// Gathers the subset of `flat.indices` contributed by the given polygon
+/// ranges (e.g. the polygons belonging to a single `g`/`usemtl` group),
+/// remapped against `flat.index_offsets`.
+pub(crate) fn group_indices(flat: &FlatMesh, polygon_ranges: &[Range<usize>]) -> Vec<u32> {
+    polygon_ranges
+        .iter()
+        .flat_map(|range| {
+            let start = flat.index_offsets[range.start];
+            let end = flat.index_offsets[range.end];
+            flat.indices[start..end].iter().copied()
+        })
+        .collect()
+}
+
+/// Builds a `Mesh` out of the subset of `flat`'s vertex buffers that
+/// `indices` actually references, remapping `indices` against a compact
+/// `0..k` vertex buffer rather than keeping `flat`'s full buffers around.
+/// This is what keeps a file with many `o`/`g`/material groups from
+/// multiplying its total vertex-buffer memory by the group count.
+pub(crate) fn build_mesh(flat: &FlatMesh, indices: Vec<u32>) -> Mesh {
+    let mut remap = HashMap::new();
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+    let mut tangents = flat.tangents.as_ref().map(|_| Vec::new());
+    let mut remapped_indices = Vec::with_capacity(indices.len());
+
+    for old_index in indices {
+        let new_index = *remap.entry(old_index).or_insert_with(|| {
+            let new_index = positions.len() as u32;
+            positions.push(flat.positions[old_index as usize]);
+            normals.push(flat.normals[old_index as usize]);
+            uvs.push(flat.uvs[old_index as usize]);
+            if let (Some(tangents), Some(flat_tangents)) = (tangents.as_mut(), &flat.tangents) {
+                tangents.push(flat_tangents[old_index as usize]);
+            }
+            new_index
+        });
+        remapped_indices.push(new_index);
+    }
+
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    set_position_data(&mut mesh, positions);
+    set_normal_data(&mut mesh, normals);
+    set_uv_data(&mut mesh, uvs);
+    if let Some(tangents) = tangents {
+        mesh.set_attribute(
+            Mesh::ATTRIBUTE_TANGENT,
+            VertexAttributeValues::Float32x4(tangents),
+        );
+    }
+    mesh.set_indices(Some(Indices::U32(remapped_indices)));
+    mesh
+}
+
+pub(crate) fn set_position_data(mesh: &mut Mesh, data: Vec<[f32; 3]>) {
+    let positions = VertexAttributeValues::Float32x3(data);
+    mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+}
+
+pub(crate) fn set_normal_data(mesh: &mut Mesh, data: Vec<[f32; 3]>) {
+    let normals = VertexAttributeValues::Float32x3(data);
+    mesh.set_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+}
+
+pub(crate) fn set_uv_data(mesh: &mut Mesh, data: Vec<[f32; 2]>) {
+    let uvs = VertexAttributeValues::Float32x2(data);
+    mesh.set_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_pnt_picks_the_least_complete_vertex_format_present() {
+        assert_eq!(detect_pnt(&[Polygon::PTN(vec![(0, 0, 0), (1, 1, 1), (2, 2, 2)])]), 3);
+        assert_eq!(
+            detect_pnt(&[
+                Polygon::PTN(vec![(0, 0, 0), (1, 1, 1), (2, 2, 2)]),
+                Polygon::PN(vec![(0, 0), (1, 1), (2, 2)]),
+            ]),
+            2
+        );
+        assert_eq!(
+            detect_pnt(&[
+                Polygon::PTN(vec![(0, 0, 0), (1, 1, 1), (2, 2, 2)]),
+                Polygon::P(vec![0, 1, 2]),
+            ]),
+            1
+        );
+    }
+
+    #[test]
+    fn compute_smooth_normals_matches_the_face_normal_for_a_single_triangle() {
+        let positions = vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+        let indices = vec![0, 1, 2];
+
+        let normals = compute_smooth_normals(&positions, &indices);
+
+        for normal in normals {
+            assert!((normal[0] - 0.0).abs() < 1e-6);
+            assert!((normal[1] - 0.0).abs() < 1e-6);
+            assert!((normal[2] - 1.0).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn compute_smooth_normals_skips_degenerate_triangles() {
+        // All three indices collapse onto the same point, so the face
+        // normal's cross product is zero and must be skipped rather than
+        // normalized into a NaN.
+        let positions = vec![[0.0, 0.0, 0.0]];
+        let indices = vec![0, 0, 0];
+
+        let normals = compute_smooth_normals(&positions, &indices);
+
+        assert_eq!(normals, vec![[0.0, 1.0, 0.0]]);
+    }
+
+    // Covers the `o`/`g` group-splitting logic added for chunk0-2, not
+    // chunk0-3 (whose tests are above) — `group_indices` is what 68d4ac0's
+    // single-named-group fix relies on to slice the flat buffer correctly.
+    #[test]
+    fn group_indices_slices_the_flat_index_buffer_by_polygon_range() {
+        let flat = FlatMesh {
+            positions: vec![],
+            normals: vec![],
+            uvs: vec![],
+            indices: vec![0, 1, 2, 3, 4, 5],
+            index_offsets: vec![0, 3, 6],
+            tangents: None,
+        };
+
+        assert_eq!(group_indices(&flat, &[0..1]), vec![0, 1, 2]);
+        assert_eq!(group_indices(&flat, &[1..2]), vec![3, 4, 5]);
+        assert_eq!(group_indices(&flat, &[0..2]), vec![0, 1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn build_mesh_compacts_vertex_buffers_to_only_referenced_vertices() {
+        let flat = FlatMesh {
+            positions: vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [2.0, 0.0, 0.0], [3.0, 0.0, 0.0]],
+            normals: vec![[0.0, 1.0, 0.0]; 4],
+            uvs: vec![[0.0, 0.0], [1.0, 0.0], [2.0, 0.0], [3.0, 0.0]],
+            indices: vec![0, 1, 2, 1, 2, 3],
+            index_offsets: vec![0, 3, 6],
+            tangents: None,
+        };
+
+        // A partial, overlapping subset of the flat buffer, as a single
+        // `o`/`g`/`usemtl` group would carve out of the whole file. Vertex 0
+        // is unreferenced and vertex 1 is repeated.
+        let mesh = build_mesh(&flat, vec![1, 2, 3, 1]);
+
+        let positions = match mesh.attribute(Mesh::ATTRIBUTE_POSITION) {
+            Some(VertexAttributeValues::Float32x3(positions)) => positions.clone(),
+            other => panic!("expected Float32x3 positions, got {:?}", other),
+        };
+        assert_eq!(
+            positions,
+            vec![[1.0, 0.0, 0.0], [2.0, 0.0, 0.0], [3.0, 0.0, 0.0]],
+            "vertex buffer should be compacted to the 3 referenced vertices, not all 4"
+        );
+
+        let indices = match mesh.indices() {
+            Some(Indices::U32(indices)) => indices.clone(),
+            other => panic!("expected U32 indices, got {:?}", other),
+        };
+        assert_eq!(
+            indices,
+            vec![0, 1, 2, 0],
+            "indices should be remapped against the compact buffer, with repeats mapping consistently"
+        );
+    }
+
+    // Covers the mikktspace tangent generation added for chunk0-4, not
+    // chunk0-3.
+    #[test]
+    fn compute_tangents_produces_unit_length_tangents_for_a_quad() {
+        let positions = vec![
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [1.0, 1.0, 0.0],
+            [0.0, 1.0, 0.0],
+        ];
+        let normals = vec![[0.0, 0.0, 1.0]; 4];
+        let uvs = vec![[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+        let indices = vec![0, 1, 2, 0, 2, 3];
+
+        let tangents = compute_tangents(&positions, &normals, &uvs, &indices)
+            .expect("mikktspace should generate tangents for a well-formed quad");
+
+        assert_eq!(tangents.len(), positions.len());
+        for tangent in tangents {
+            let length_squared =
+                tangent[0] * tangent[0] + tangent[1] * tangent[1] + tangent[2] * tangent[2];
+            assert!((length_squared - 1.0).abs() < 1e-3);
+            assert!(tangent[3] == 1.0 || tangent[3] == -1.0);
+        }
+    }
+}
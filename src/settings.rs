@@ -0,0 +1,37 @@
+/// Options controlling how [`ObjLoader`](crate::ObjLoader) converts an OBJ
+/// file's raw data into Bevy's conventions.
+///
+/// OBJ has no fixed handedness or UV origin, so exporters disagree on both;
+/// these settings give callers deterministic control over the conversion
+/// instead of the loader silently guessing.
+#[derive(Debug, Clone, Copy)]
+pub struct ObjLoaderSettings {
+    /// Remaps a `[x, y, z]` position or normal from the OBJ file's axis
+    /// convention into the target one. Defaults to the identity (no
+    /// conversion, i.e. OBJ's Y-up right-handed space is kept as-is).
+    pub coordinate_conversion: fn([f32; 3]) -> [f32; 3],
+    /// Whether to flip the V component of texture coordinates
+    /// (`v' = 1.0 - v`). Most OBJ exporters put the UV origin at the
+    /// bottom-left, which this corrects for Bevy's top-left convention.
+    /// Defaults to `true`.
+    pub flip_uv_y: bool,
+    /// Whether to generate mikktspace tangents when the file has both
+    /// normals and UVs. Defaults to `true`.
+    pub generate_tangents: bool,
+}
+
+impl Default for ObjLoaderSettings {
+    fn default() -> Self {
+        Self {
+            coordinate_conversion: |position| position,
+            flip_uv_y: true,
+            generate_tangents: true,
+        }
+    }
+}
+
+/// A coordinate conversion that swaps OBJ's `[x, y, z]` into `[x, z, -y]`,
+/// useful when importing assets authored for a Z-up target convention.
+pub fn xzy_coordinate_conversion(position: [f32; 3]) -> [f32; 3] {
+    [position[0], position[2], -position[1]]
+}